@@ -1,17 +1,28 @@
+mod backend;
+
 use structopt::StructOpt;
 
-use chrono::{DateTime, FixedOffset, NaiveDateTime};
-use git2::{Commit, Object, Oid, Repository, Time};
+use backend::RepoBackend;
+use chrono::format::StrftimeItems;
+use chrono::{DateTime, FixedOffset};
 use serde_json::{map::Map, value::Value};
 use std::collections::HashMap;
 use std::{error::Error, path::PathBuf};
 use std::str::FromStr;
+use time::OffsetDateTime;
+
+#[cfg(not(feature = "gix-backend"))]
+type ActiveBackend = backend::git2_backend::Git2Backend;
+#[cfg(feature = "gix-backend")]
+type ActiveBackend = backend::gix_backend::GixBackend;
 
 
 #[derive(Debug)]
 enum OutputFormat {
     Json,
     Csv,
+    Changelog,
+    Dot,
 }
 
 
@@ -21,11 +32,38 @@ impl FromStr for OutputFormat {
         match s.to_lowercase().as_str() {
             "json" => Ok(OutputFormat::Json),
             "csv" => Ok(OutputFormat::Csv),
-            _ => Err("Only JSON and CSV outputs are supported.".into()),
+            "changelog" => Ok(OutputFormat::Changelog),
+            "dot" => Ok(OutputFormat::Dot),
+            _ => Err("Only JSON, CSV, changelog and dot outputs are supported.".into()),
         }
     }
 }
 
+/// A single `type=Heading` mapping parsed from `--changelog-sections`.
+#[derive(Debug, Clone)]
+struct ChangelogSection {
+    commit_type: String,
+    heading: String,
+}
+
+fn parse_changelog_sections(spec: &str) -> Vec<ChangelogSection> {
+    spec.split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.splitn(2, '=');
+            let commit_type = parts.next()?.trim();
+            let heading = parts.next()?.trim();
+            if commit_type.is_empty() || heading.is_empty() {
+                None
+            } else {
+                Some(ChangelogSection {
+                    commit_type: commit_type.to_string(),
+                    heading: heading.to_string(),
+                })
+            }
+        })
+        .collect()
+}
+
 #[derive(Debug, StructOpt)]
 #[structopt(name = "example", about = "An example of StructOpt usage.")]
 struct Args {
@@ -47,118 +85,132 @@ struct Args {
 
     /// Order
     #[structopt(long)]
-    oldest_first: bool
+    oldest_first: bool,
+
+    /// Section mapping for `--outputformat changelog`, e.g. "feat=Features,fix=Bug Fixes"
+    #[structopt(long, default_value = "feat=Features,fix=Bug Fixes")]
+    changelog_sections: String,
+
+    /// Lines of context around each diff hunk, used by the `patch` field
+    #[structopt(long, default_value = "3")]
+    context_lines: u32,
+
+    /// strftime-style pattern used by the `ad`/`aD`/`ai`/`cd`/`cD`/`ci` fields
+    /// (`ar`/`cr` always render relative dates, e.g. "3 days ago")
+    #[structopt(long)]
+    date_format: Option<String>,
 }
 
 #[paw::main]
 fn main(args: Args) -> Result<(), Box<dyn Error>> {
     // let args: Vec<String> = env::args().collect();
+    let repository = ActiveBackend::open(&args.repo.unwrap_or_else(|| ".".into()))?;
     print_commits(
+        &repository,
         &args.range,
         &args.fields,
-        args.repo,
         args.outputformat,
         args.oldest_first,
+        &args.changelog_sections,
+        args.context_lines,
+        args.date_format.as_deref(),
     )?;
     Ok(())
 }
 
-fn print_commits(
+fn print_commits<B: RepoBackend>(
+    repository: &B,
     revision_range: &str,
     formats_input: &str,
-    path: Option<PathBuf>,
     output_format: OutputFormat,
     oldest_first: bool,
+    changelog_sections: &str,
+    context_lines: u32,
+    date_format: Option<&str>,
 ) -> Result<(), Box<dyn Error>> {
-    let repository: &mut Repository = &mut Repository::open(
-        path.unwrap_or_else(|| ".".into())
-    )?;
-
     let formats = formats_input.split(',').collect::<Vec<&str>>();
-    let mut reference_map: HashMap<Oid, Vec<String>> = HashMap::new();
-
-    if formats.contains(&"D") {
-        for reference_result in repository.references()? {
-            let reference = reference_result?;
-            let ref_shorthand = match reference.shorthand() {
-                Some(shorthand) => shorthand,
-                None => continue,
-            };
-            let ref_target = reference.peel_to_commit()?.id();
-            reference_map
-                .entry(ref_target)
-                .or_insert_with(Vec::new)
-                .push(ref_shorthand.to_string());
-        }
+
+    let mut reference_map: HashMap<String, Vec<String>> = HashMap::new();
+    if formats.contains(&"D") || matches!(output_format, OutputFormat::Dot) {
+        reference_map = repository.reference_tips()?;
     }
 
-    let mut revwalk = repository.revwalk()?;
-    if oldest_first {
-        revwalk.set_sorting(git2::Sort::REVERSE)?;
+    let ids = repository.walk(revision_range, oldest_first)?;
+
+    if let OutputFormat::Changelog = output_format {
+        return print_changelog(repository, &ids, changelog_sections);
+    }
+    if let OutputFormat::Dot = output_format {
+        return print_dot(repository, &ids, &formats, &reference_map);
     }
-    revwalk.push_range(revision_range)?;
 
-    let mut prevcommit: Option<Commit> = None;
-    // println!("{}", &formats.iter().map(|f| Value::String(f.to_string()).to_string()).collect::<Vec<_>>().join(","));
+    let diff_requested = formats
+        .iter()
+        .any(|f| matches!(*f, "df" | "di" | "dd" | "patch" | "name-status"));
+
     let mut print_header = true;
-    for oid in revwalk {
-        let oidr = oid?;
-        let commit = repository.find_commit(oidr)?;
-
-        let prevtree = prevcommit.map(|pc| pc.tree().unwrap());
-        let diffstats = repository
-            .diff_tree_to_tree(prevtree.as_ref(), commit.tree().ok().as_ref(), None)
-            .map(|diff| diff.stats().ok())
-            .ok()
-            .flatten();
-
-        prevcommit = Some(commit.clone());
+    for id in &ids {
+        let commit = repository.commit(id)?;
+        let diff = if diff_requested {
+            Some(repository.diff(commit.parent_ids.first().map(String::as_str), id, context_lines)?)
+        } else {
+            None
+        };
+
+        let conventional = parse_conventional_commit(&commit.message);
         let mut map = Map::new();
         for format in &formats {
             map.insert(format.to_string(), match *format {
-                "H" => Value::String(oid_to_hex_string(commit.id())),
-                "h" => Value::String(object_to_hex_string(commit.as_object())?),
-                "T" => Value::String(oid_to_hex_string(commit.tree_id())),
-                "t" => Value::String(object_to_hex_string(commit.tree()?.as_object())?),
-                "P" => commit.parent_ids().map(oid_to_hex_string).map(Value::String).collect::<Value>(),
-                "p" => commit.parents()
-                    .map(|parent| Ok(Value::String(object_to_hex_string(parent.as_object())?)))
-                    .collect::<Result<Value, Box<dyn Error>>>()?,
-                "an" => Value::String(commit.author().name().ok_or("Author name contains invalid UTF8")?.to_string()),
-                "ae" => Value::String(commit.author().email().ok_or("Author email contains invalid UTF8")?.to_string()),
-                "aN" | "aE" => invalid_format(format, "Mailmaps not currently supported, consider using `an`/`ae` instead of `aN`/`aE`")?,
-                "at" => Value::Number(commit.author().when().seconds().into()),
-                "aI" => Value::String(git_time_to_iso8601(commit.author().when())),
-                "ad" | "aD" | "ar" | "ai" => invalid_format(format, "Formatted dates not supported, use `aI` and format the date yourself")?,
-                "ct" => Value::Number(commit.time().seconds().into()),
-                "cI" => Value::String(git_time_to_iso8601(commit.time())),
-                "cd" | "cD" | "cr" | "ci" => invalid_format(format, "Formatted dates not supported, use `cI` and format the date yourself")?,
+                "H" => Value::String(commit.id.clone()),
+                "h" => Value::String(commit.short_id.clone()),
+                "T" => Value::String(commit.tree_id.clone()),
+                "t" => Value::String(commit.short_tree_id.clone()),
+                "P" => commit.parent_ids.iter().cloned().map(Value::String).collect::<Value>(),
+                "p" => commit.short_parent_ids.iter().cloned().map(Value::String).collect::<Value>(),
+                "an" => Value::String(commit.author_name.clone()),
+                "ae" => Value::String(commit.author_email.clone()),
+                "aN" => Value::String(repository.resolve_mailmap(&commit.author_name, &commit.author_email).0),
+                "aE" => Value::String(repository.resolve_mailmap(&commit.author_name, &commit.author_email).1),
+                "cN" => Value::String(repository.resolve_mailmap(&commit.committer_name, &commit.committer_email).0),
+                "cE" => Value::String(repository.resolve_mailmap(&commit.committer_name, &commit.committer_email).1),
+                "at" => Value::Number(commit.author_time_seconds.into()),
+                "aI" => Value::String(git_time_to_iso8601(commit.author_time_seconds, commit.author_offset_minutes)),
+                "ad" | "aD" | "ai" => match date_format {
+                    Some(pattern) => Value::String(format_git_time(commit.author_time_seconds, commit.author_offset_minutes, pattern)?),
+                    None => invalid_format(format, "Formatted dates require --date-format")?,
+                },
+                "ar" => Value::String(format_relative_time(commit.author_time_seconds)),
+                "ct" => Value::Number(commit.committer_time_seconds.into()),
+                "cI" => Value::String(git_time_to_iso8601(commit.committer_time_seconds, commit.committer_offset_minutes)),
+                "cd" | "cD" | "ci" => match date_format {
+                    Some(pattern) => Value::String(format_git_time(commit.committer_time_seconds, commit.committer_offset_minutes, pattern)?),
+                    None => invalid_format(format, "Formatted dates require --date-format")?,
+                },
+                "cr" => Value::String(format_relative_time(commit.committer_time_seconds)),
                 "d" => invalid_format(format, "Formatted ref names not supported, use `D` and format the names yourself")?,
                 "D" => reference_map
-                    .remove(&commit.id())
+                    .remove(&commit.id)
                     .unwrap_or_else(Vec::new)
                     .into_iter()
                     .map(Value::String)
                     .collect::<Value>(),
-                "s" => Value::String(commit.summary().ok_or("Commit header contains invalid UTF8")?.to_string()),
+                "s" => Value::String(commit.summary.clone()),
                 "b" => invalid_format(format, "Body not supported, use `B` and extract the body yourself")?,
-                "B" => Value::String(commit.message().ok_or("Commit message contains invalid UTF8")?.to_string()),
+                "B" => Value::String(commit.message.clone()),
                 "N" => invalid_format(format, "Notes not currently supported")?,
-                "df" => {
-                    Value::String(
-                        diffstats.as_ref().map(|ds| ds.files_changed().to_string()).unwrap_or_default()
-                    )
-                },
-                "di" => {
-                    Value::String(
-                        diffstats.as_ref().map(|ds| ds.insertions().to_string()).unwrap_or_default()
-                    )
-                },
-                "dd" => {
-                    Value::String(
-                        diffstats.as_ref().map(|ds| ds.deletions().to_string()).unwrap_or_default()
-                    )
-                },
+                "df" => Value::String(diff.as_ref().map(|d| d.files_changed.to_string()).unwrap_or_default()),
+                "di" => Value::String(diff.as_ref().map(|d| d.insertions.to_string()).unwrap_or_default()),
+                "dd" => Value::String(diff.as_ref().map(|d| d.deletions.to_string()).unwrap_or_default()),
+                "patch" => Value::String(diff.as_ref().map(|d| d.patch.clone()).unwrap_or_default()),
+                "name-status" => Value::String(diff.as_ref().map(|d| d.name_status.clone()).unwrap_or_default()),
+                "ctype" => conventional.as_ref().map(|c| Value::String(c.commit_type.clone())).unwrap_or(Value::Null),
+                "cscope" => conventional.as_ref()
+                    .and_then(|c| c.scope.clone())
+                    .map(Value::String)
+                    .unwrap_or(Value::Null),
+                "cbreaking" => conventional.as_ref().map(|c| Value::Bool(c.breaking)).unwrap_or(Value::Null),
+                "cdesc" => conventional.as_ref().map(|c| Value::String(c.description.clone())).unwrap_or(Value::Null),
+                "cfooters" => conventional.as_ref().map(|c| Value::Object(c.footers.clone())).unwrap_or(Value::Null),
                 "GG" | "G?" | "GS" | "GK" => invalid_format(format, "Signatures not currently supported")?,
                 _ => invalid_format(format, "Not found")?
             });
@@ -180,32 +232,247 @@ fn print_commits(
             OutputFormat::Json => {
                 println!("{}", Value::Object(map));
             }
+            OutputFormat::Changelog | OutputFormat::Dot => unreachable!("handled above"),
         }
     }
     Ok(())
 }
 
-fn oid_to_hex_string(oid: Oid) -> String {
-    oid.as_bytes()
-        .iter()
-        .map(|byte| format!("{:02x}", byte))
-        .collect::<String>()
+/// The parsed parts of a Conventional Commit message
+/// (https://www.conventionalcommits.org/), used to expose `c*` fields.
+#[derive(Debug, Default)]
+struct ConventionalCommit {
+    commit_type: String,
+    scope: Option<String>,
+    breaking: bool,
+    description: String,
+    footers: Map<String, Value>,
+}
+
+fn is_footer_line(line: &str) -> bool {
+    if line.starts_with("BREAKING CHANGE: ") {
+        return true;
+    }
+    match line.find(": ") {
+        Some(idx) => {
+            let key = &line[..idx];
+            !key.is_empty() && key.chars().all(|c| c.is_ascii_alphabetic() || c == '-')
+        }
+        None => false,
+    }
+}
+
+fn parse_conventional_commit(message: &str) -> Option<ConventionalCommit> {
+    let mut parts = message.splitn(2, '\n');
+    let header = parts.next().unwrap_or("").trim_end_matches('\r');
+    let remainder = parts.next().unwrap_or("");
+
+    let colon_idx = header.find(": ")?;
+    let (prefix, desc) = header.split_at(colon_idx);
+    let description = desc[2..].to_string();
+
+    let breaking_bang = prefix.ends_with('!');
+    let prefix = if breaking_bang { &prefix[..prefix.len() - 1] } else { prefix };
+
+    let (commit_type, scope) = match prefix.find('(') {
+        Some(open) if prefix.ends_with(')') => (
+            prefix[..open].to_string(),
+            Some(prefix[open + 1..prefix.len() - 1].to_string()),
+        ),
+        Some(_) => return None,
+        None => (prefix.to_string(), None),
+    };
+
+    if commit_type.is_empty() {
+        return None;
+    }
+
+    let after_blank = remainder.strip_prefix('\n').unwrap_or(remainder);
+    let mut lines: Vec<&str> = after_blank.lines().collect();
+    let mut footers = Map::new();
+    let mut breaking = breaking_bang;
+
+    while let Some(last) = lines.last() {
+        if last.is_empty() {
+            lines.pop();
+            continue;
+        }
+        if is_footer_line(last) {
+            let line = lines.pop().unwrap();
+            if let Some(value) = line.strip_prefix("BREAKING CHANGE: ") {
+                footers.insert("BREAKING CHANGE".to_string(), Value::String(value.to_string()));
+                breaking = true;
+            } else if let Some(idx) = line.find(": ") {
+                footers.insert(line[..idx].to_string(), Value::String(line[idx + 2..].to_string()));
+            }
+        } else {
+            break;
+        }
+    }
+
+    Some(ConventionalCommit {
+        commit_type,
+        scope,
+        breaking,
+        description,
+        footers,
+    })
 }
 
-fn object_to_hex_string(object: &Object) -> Result<String, Box<dyn Error>> {
-    match object.short_id()?.as_str() {
-        Some(shorthash) => Ok(shorthash.to_string()),
-        None => Err("libgit returned a bad shorthash".into()),
+/// Groups the commits in `ids` by Conventional Commit type and prints a
+/// Markdown changelog document, one section per entry in `changelog_sections`
+/// that has at least one commit (empty sections are omitted), plus a trailing
+/// "Other" bucket for unmatched or non-conventional commits.
+fn print_changelog<B: RepoBackend>(
+    repository: &B,
+    ids: &[String],
+    changelog_sections: &str,
+) -> Result<(), Box<dyn Error>> {
+    let sections = parse_changelog_sections(changelog_sections);
+    let mut grouped: Vec<Vec<String>> = vec![Vec::new(); sections.len()];
+    let mut other: Vec<String> = Vec::new();
+
+    for id in ids {
+        let commit = repository.commit(id)?;
+
+        let bullet = match parse_conventional_commit(&commit.message) {
+            Some(c) => {
+                let scope = c.scope.map(|s| format!("**{}:** ", s)).unwrap_or_default();
+                let breaking = if c.breaking { "**BREAKING:** " } else { "" };
+                let bullet = format!("- `{}` {}{}{}", commit.short_id, breaking, scope, c.description);
+                match sections.iter().position(|s| s.commit_type == c.commit_type) {
+                    Some(idx) => {
+                        grouped[idx].push(bullet);
+                        continue;
+                    }
+                    None => bullet,
+                }
+            }
+            None => format!("- `{}` {}", commit.short_id, commit.summary),
+        };
+        other.push(bullet);
     }
+
+    for (section, entries) in sections.iter().zip(grouped.iter()) {
+        if entries.is_empty() {
+            continue;
+        }
+        println!("## {}\n", section.heading);
+        for entry in entries {
+            println!("{}", entry);
+        }
+        println!();
+    }
+
+    if !other.is_empty() {
+        println!("## Other\n");
+        for entry in &other {
+            println!("{}", entry);
+        }
+    }
+
+    Ok(())
 }
 
-fn git_time_to_iso8601(time: Time) -> String {
-    let time_without_zone = NaiveDateTime::from_timestamp(time.seconds(), 0);
-    let time_with_zone = DateTime::<FixedOffset>::from_utc(
-        time_without_zone,
-        FixedOffset::east(time.offset_minutes() * 60),
-    );
-    time_with_zone.to_rfc3339()
+/// Walks `ids` and writes a Graphviz `digraph` of the commit DAG: one node
+/// per commit, labeled from whichever of `fields` apply (`h`, `H`, `s`, `D`), and
+/// one edge per parent. Merge commits and reference tips get distinct node
+/// styling.
+fn print_dot<B: RepoBackend>(
+    repository: &B,
+    ids: &[String],
+    fields: &[&str],
+    reference_map: &HashMap<String, Vec<String>>,
+) -> Result<(), Box<dyn Error>> {
+    println!("digraph {{");
+    for id in ids {
+        let commit = repository.commit(id)?;
+
+        let mut label_parts: Vec<String> = Vec::new();
+        for field in fields {
+            match *field {
+                "h" => label_parts.push(commit.short_id.clone()),
+                "H" => label_parts.push(commit.id.clone()),
+                "s" => label_parts.push(commit.summary.clone()),
+                "D" => {
+                    if let Some(refs) = reference_map.get(&commit.id) {
+                        if !refs.is_empty() {
+                            label_parts.push(refs.join(", "));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        let label = if label_parts.is_empty() {
+            commit.id.clone()
+        } else {
+            label_parts.join("\\n")
+        };
+
+        let mut attrs = vec![format!("label=\"{}\"", label.replace('"', "\\\""))];
+        if commit.parent_ids.len() > 1 {
+            attrs.push("shape=box".to_string());
+        }
+        if reference_map.contains_key(&commit.id) {
+            attrs.push("color=blue".to_string());
+            attrs.push("penwidth=2".to_string());
+        }
+        println!("  \"{}\" [{}];", commit.id, attrs.join(", "));
+
+        for parent_id in &commit.parent_ids {
+            println!("  \"{}\" -> \"{}\";", commit.id, parent_id);
+        }
+    }
+    println!("}}");
+    Ok(())
+}
+
+/// Builds the `DateTime<FixedOffset>` shared by `git_time_to_iso8601` and
+/// `format_git_time` from a commit's raw (seconds, UTC-offset-minutes) pair.
+fn git_time_to_datetime(seconds: i64, offset_minutes: i32) -> Option<DateTime<FixedOffset>> {
+    let offset = FixedOffset::east_opt(offset_minutes * 60)?;
+    let utc = DateTime::from_timestamp(seconds, 0)?;
+    Some(utc.with_timezone(&offset))
+}
+
+fn git_time_to_iso8601(seconds: i64, offset_minutes: i32) -> String {
+    git_time_to_datetime(seconds, offset_minutes)
+        .map(|dt| dt.to_rfc3339())
+        .unwrap_or_default()
+}
+
+/// Formats a commit time with a real strftime pattern. Validates `pattern`
+/// with `StrftimeItems` first, since `DateTime::format(...).to_string()`
+/// panics on an unrecognized specifier instead of erroring.
+fn format_git_time(seconds: i64, offset_minutes: i32, pattern: &str) -> Result<String, Box<dyn Error>> {
+    StrftimeItems::new(pattern)
+        .parse()
+        .map_err(|_| format!("Invalid --date-format pattern `{}`", pattern))?;
+    let datetime = git_time_to_datetime(seconds, offset_minutes)
+        .ok_or_else(|| format!("Commit time {} is out of range", seconds))?;
+    Ok(datetime.format(pattern).to_string())
+}
+
+/// Renders a unix timestamp as a git-style "N units ago" relative date.
+fn format_relative_time(seconds: i64) -> String {
+    let then = OffsetDateTime::from_unix_timestamp(seconds).unwrap_or(OffsetDateTime::UNIX_EPOCH);
+    let seconds = (OffsetDateTime::now_utc() - then).whole_seconds().max(0);
+
+    let (amount, unit) = if seconds < 60 {
+        (seconds, "second")
+    } else if seconds < 60 * 60 {
+        (seconds / 60, "minute")
+    } else if seconds < 60 * 60 * 24 {
+        (seconds / (60 * 60), "hour")
+    } else if seconds < 60 * 60 * 24 * 30 {
+        (seconds / (60 * 60 * 24), "day")
+    } else if seconds < 60 * 60 * 24 * 365 {
+        (seconds / (60 * 60 * 24 * 30), "month")
+    } else {
+        (seconds / (60 * 60 * 24 * 365), "year")
+    };
+    format!("{} {}{} ago", amount, unit, if amount == 1 { "" } else { "s" })
 }
 
 fn invalid_format(format: &str, reason: &str) -> Result<Value, Box<dyn Error>> {